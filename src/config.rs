@@ -0,0 +1,62 @@
+use std::{collections::HashMap, fs};
+
+use serde::Deserialize;
+
+use crate::NAME;
+
+/// A single named Home Assistant server profile, as read from the config file.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ProfileConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub access_token: Option<String>,
+    pub scheme: Option<String>,
+    pub ca_cert: Option<String>,
+    pub insecure: Option<bool>,
+}
+
+/// Layered configuration loaded from `~/.config/hassctl/config.toml`.
+///
+/// Environment variables always take precedence over values found here;
+/// this file only fills in what the environment doesn't provide.
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profile: HashMap<String, ProfileConfig>,
+}
+
+impl Config {
+    /// Loads the config file if present. A missing file is treated as an
+    /// empty config, so `hassctl` keeps working with only environment
+    /// variables set, as before. A file that exists but fails to parse is
+    /// also treated as empty, but is reported so the user isn't left
+    /// guessing why their profiles weren't picked up.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(err) => {
+                println!(
+                    "Warning: failed to parse config file {}, ignoring it:\n{}",
+                    path.display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    fn path() -> Option<std::path::PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(NAME).join("config.toml"))
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&ProfileConfig> {
+        self.profile.get(name)
+    }
+}
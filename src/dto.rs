@@ -3,6 +3,27 @@ use std::collections::HashMap;
 use serde::Deserialize;
 use serde_json::Value;
 
+#[derive(Deserialize, Debug)]
+pub struct WsMessage {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub event: Option<WsEvent>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WsEvent {
+    pub event_type: String,
+    pub data: StateChangedEventDto,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StateChangedEventDto {
+    pub entity_id: String,
+    pub old_state: Option<StateDto>,
+    pub new_state: Option<StateDto>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct StateDto {
     pub attributes: HashMap<String, serde_json::Value>,
@@ -1,23 +1,39 @@
+mod config;
 mod dto;
+mod watch;
 
-use std::{collections::HashMap, env};
+use std::{collections::HashMap, env, fs};
 
 use clap::{Parser, Subcommand};
-use dialoguer::{theme::ColorfulTheme, FuzzySelect};
+use config::Config;
+use dialoguer::{theme::ColorfulTheme, FuzzySelect, Input, Password};
 use dto::{ServiceDto, StateDto};
 use reqwest::Error;
+use secrecy::{ExposeSecret, SecretString};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
 
 const NAME: &str = "hassctl";
 const ACCESS_TOKEN_KEY: &str = "HASSCTL_ACCESS_TOKEN";
 const PORT_KEY: &str = "HASSCTL_PORT";
 const DEFAULT_PORT: u16 = 8123;
 const HOST_KEY: &str = "HASSCTL_HOST";
+const SCHEME_KEY: &str = "HASSCTL_SCHEME";
+const CA_CERT_KEY: &str = "HASSCTL_CA_CERT";
+const INSECURE_KEY: &str = "HASSCTL_INSECURE";
+const KEYRING_SERVICE: &str = "hassctl";
+const DEFAULT_PROFILE: &str = "default";
+
+fn keyring_entry(profile: &str, host: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, &format!("{}:{}", profile, host))
+}
 
 struct Client {
-    access_token: String,
+    access_token: SecretString,
+    scheme: String,
     port: u16,
     host: String,
+    http: reqwest::blocking::Client,
 }
 
 enum ClientError {
@@ -26,6 +42,9 @@ enum ClientError {
     MissingHost,
     InvalidHost,
     InvalidPort,
+    InvalidScheme,
+    InvalidCaCert,
+    TlsSetupFailed,
 }
 
 impl ClientError {
@@ -42,64 +61,134 @@ impl ClientError {
                 and make sure to copy the token.\n\
                 \n\
                 Then create an environment variable named {}\n\
-                with the access token as value.",
-                NAME, ACCESS_TOKEN_KEY
+                with the access token as value, or add it to a profile\n\
+                in the {} config file.",
+                NAME, ACCESS_TOKEN_KEY, NAME
             ),
             ClientError::InvalidAccessToken => "Invalid access token!".into(),
             ClientError::MissingHost => format!(
                 "Missing host.\n\
                 \n\
-                Host must be specified in the environment variable {}.\n",
-                HOST_KEY
+                Host must be specified in the environment variable {}\n\
+                or in a profile in the {} config file.\n",
+                HOST_KEY, NAME
             )
             .into(),
             ClientError::InvalidHost => "Invalid host.".into(),
             ClientError::InvalidPort => "Invalid port.".into(),
+            ClientError::InvalidScheme => "Invalid scheme.".into(),
+            ClientError::InvalidCaCert => format!(
+                "Invalid CA certificate.\n\
+                \n\
+                The file given in {} (or a profile's `ca_cert`) could not\n\
+                be read or parsed as a PEM-encoded certificate.",
+                CA_CERT_KEY
+            ),
+            ClientError::TlsSetupFailed => "Failed to set up TLS for the HTTP client.".into(),
         }
     }
 }
 
 impl Client {
-    fn setup() -> Result<Self, ClientError> {
-        let access_token = match env::var(ACCESS_TOKEN_KEY) {
+    /// Sets up a client for the given profile, falling back to the config
+    /// file's `default_profile` when `profile` is `None`. Environment
+    /// variables always override whatever the selected profile provides.
+    fn setup(profile: Option<&str>) -> Result<Self, ClientError> {
+        let config = Config::load();
+        let profile_name = profile
+            .map(str::to_string)
+            .or_else(|| config.default_profile.clone());
+        let profile_cfg = profile_name.as_deref().and_then(|name| config.profile(name));
+        let profile_key = profile_name.as_deref().unwrap_or(DEFAULT_PROFILE);
+
+        let host = match env::var(HOST_KEY) {
             Ok(s) => s,
-            Err(env::VarError::NotPresent) => match dotenv::var(ACCESS_TOKEN_KEY) {
-                Ok(s) => s,
-                Err(_) => return Err(ClientError::MissingAccessToken),
+            Err(env::VarError::NotPresent) => match profile_cfg.and_then(|p| p.host.clone()) {
+                Some(s) => s,
+                None => return Err(ClientError::MissingHost),
             },
-            Err(_) => return Err(ClientError::InvalidAccessToken),
+            Err(_) => return Err(ClientError::InvalidHost),
         };
 
-        let host = match env::var(HOST_KEY) {
+        let access_token = match keyring_entry(profile_key, &host).and_then(|e| e.get_password())
+        {
             Ok(s) => s,
-            Err(env::VarError::NotPresent) => return Err(ClientError::MissingHost),
-            Err(_) => return Err(ClientError::InvalidHost),
+            Err(_) => match env::var(ACCESS_TOKEN_KEY) {
+                Ok(s) => s,
+                Err(env::VarError::NotPresent) => match dotenv::var(ACCESS_TOKEN_KEY) {
+                    Ok(s) => s,
+                    Err(_) => match profile_cfg.and_then(|p| p.access_token.clone()) {
+                        Some(s) => s,
+                        None => return Err(ClientError::MissingAccessToken),
+                    },
+                },
+                Err(_) => return Err(ClientError::InvalidAccessToken),
+            },
         };
+        let access_token = SecretString::new(access_token);
 
         let port = match env::var(PORT_KEY) {
             Ok(s) => match s.parse::<u16>() {
                 Ok(v) => v,
                 Err(_) => return Err(ClientError::InvalidPort),
             },
-            Err(env::VarError::NotPresent) => DEFAULT_PORT,
+            Err(env::VarError::NotPresent) => {
+                profile_cfg.and_then(|p| p.port).unwrap_or(DEFAULT_PORT)
+            }
             Err(_) => return Err(ClientError::InvalidPort),
         };
 
+        let scheme = match env::var(SCHEME_KEY) {
+            Ok(s) => s,
+            Err(env::VarError::NotPresent) => profile_cfg
+                .and_then(|p| p.scheme.clone())
+                .unwrap_or_else(|| if port == 443 { "https".into() } else { "http".into() }),
+            Err(_) => return Err(ClientError::InvalidScheme),
+        };
+
+        let ca_cert_path = match env::var(CA_CERT_KEY) {
+            Ok(s) => Some(s),
+            Err(env::VarError::NotPresent) => profile_cfg.and_then(|p| p.ca_cert.clone()),
+            Err(_) => return Err(ClientError::InvalidCaCert),
+        };
+
+        let danger_accept_invalid_certs = match env::var(INSECURE_KEY) {
+            Ok(s) => s == "1" || s.eq_ignore_ascii_case("true"),
+            Err(_) => profile_cfg.and_then(|p| p.insecure).unwrap_or(false),
+        };
+
+        let mut builder = reqwest::blocking::Client::builder();
+        if let Some(path) = ca_cert_path {
+            let pem = fs::read(&path).map_err(|_| ClientError::InvalidCaCert)?;
+            let cert =
+                reqwest::Certificate::from_pem(&pem).map_err(|_| ClientError::InvalidCaCert)?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if danger_accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        let http = builder.build().map_err(|_| ClientError::TlsSetupFailed)?;
+
         Ok(Self {
             access_token,
+            scheme,
             host,
             port,
+            http,
         })
     }
 
     fn build_url(&self, path: &str) -> String {
-        format!("http://{}:{}{}", self.host, self.port, path)
+        format!("{}://{}:{}{}", self.scheme, self.host, self.port, path)
     }
 
     fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
         let url = self.build_url(path);
-        let client = reqwest::blocking::Client::new();
-        let response = client.get(url).bearer_auth(&self.access_token).send();
+        let response = self
+            .http
+            .get(url)
+            .bearer_auth(self.access_token.expose_secret())
+            .send();
         match response {
             Ok(res) => res.json::<T>(),
             Err(err) => Err(err),
@@ -108,10 +197,10 @@ impl Client {
 
     fn post<T: Serialize, R: DeserializeOwned>(&self, path: &str, payload: &T) -> Result<R, Error> {
         let url = self.build_url(path);
-        let client = reqwest::blocking::Client::new();
-        let response = client
+        let response = self
+            .http
             .post(url)
-            .bearer_auth(&self.access_token)
+            .bearer_auth(self.access_token.expose_secret())
             .json(payload)
             .send();
         match response {
@@ -140,20 +229,15 @@ impl Client {
             .collect())
     }
 
-    // FIXME: not all services require entity ID's
     fn call_service(
         &self,
         domain: &str,
         service: &str,
-        entity_id: &str,
+        payload: serde_json::Map<String, Value>,
     ) -> Result<Vec<StateDto>, Error> {
-        let payload = ServiceDataDto {
-            entity_id: entity_id.to_string(),
-        };
-
-        self.post::<ServiceDataDto, Vec<StateDto>>(
+        self.post::<Value, Vec<StateDto>>(
             format!("/api/services/{}/{}", domain, service).as_str(),
-            &payload,
+            &Value::Object(payload),
         )
     }
 }
@@ -182,6 +266,29 @@ fn cmd_entity_show(client: &Client, entity_id: &str) {
     }
 }
 
+fn cmd_entity_watch(client: &Client, entity_ids: Vec<String>, domains: Vec<String>) {
+    let rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(err) => {
+            println!("Failed to start async runtime: {:?}", err);
+            return;
+        }
+    };
+
+    let result = rt.block_on(watch::watch(
+        &client.scheme,
+        &client.host,
+        client.port,
+        &client.access_token,
+        entity_ids.into_iter().collect(),
+        domains.into_iter().collect(),
+    ));
+
+    if let Err(err) = result {
+        println!("Watch failed: {}", err);
+    }
+}
+
 fn cmd_service_list(client: &Client) {
     match client.get::<Vec<ServiceDomainDto>>("/api/services") {
         Ok(list) => {
@@ -208,27 +315,126 @@ fn prompt_for_selection(prompt: &str, options: &Vec<&str>) -> usize {
         .unwrap()
 }
 
-fn cmd_call(client: &Client) -> Result<(), Error> {
+fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) => Ok((k.to_string(), v.to_string())),
+        None => Err(format!("invalid key=value pair: {}", s)),
+    }
+}
+
+fn parse_field_value(raw: &str) -> Value {
+    if let Ok(b) = raw.parse::<bool>() {
+        return Value::Bool(b);
+    }
+    if let Ok(n) = raw.parse::<i64>() {
+        return Value::Number(n.into());
+    }
+    if let Ok(f) = raw.parse::<f64>() {
+        if let Some(num) = serde_json::Number::from_f64(f) {
+            return Value::Number(num);
+        }
+    }
+    Value::String(raw.to_string())
+}
+
+fn default_as_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn cmd_call(client: &Client, args: &CallCli) -> Result<(), Error> {
     let domains = client.fetch_services()?;
-    let domain_ids = domains.iter().map(|d| d.domain.as_str()).collect();
-    let i = prompt_for_selection("Domain", &domain_ids);
-    let domain_id = domain_ids[i];
-    let domain = domains.iter().find(|d| d.domain == domain_id).unwrap();
-
-    let service_ids = domain.services.keys().map(|k| k.as_str()).collect();
-    let i = prompt_for_selection("Service", &service_ids);
-    let service_id = service_ids[i];
-    let service = domain.services.get(service_id).unwrap();
-
-    let entities = match service.get_target_entity_domains() {
-        None => client.fetch_entities()?,
-        Some(d) => client.fetch_entities_by_domain(d)?,
+
+    let (domain_id, service_id) = match &args.service {
+        Some(spec) => match spec.split_once('.') {
+            Some((d, s)) => (d.to_string(), s.to_string()),
+            None => {
+                println!("Service must be specified as <domain>.<service>.");
+                return Ok(());
+            }
+        },
+        None => {
+            let domain_ids = domains.iter().map(|d| d.domain.as_str()).collect();
+            let i = prompt_for_selection("Domain", &domain_ids);
+            let domain_id = domain_ids[i].to_string();
+            let domain = domains.iter().find(|d| d.domain == domain_id).unwrap();
+
+            let service_ids = domain.services.keys().map(|k| k.as_str()).collect();
+            let i = prompt_for_selection("Service", &service_ids);
+            (domain_id, service_ids[i].to_string())
+        }
+    };
+
+    let domain = match domains.iter().find(|d| d.domain == domain_id) {
+        Some(d) => d,
+        None => {
+            println!("Unknown domain: {}", domain_id);
+            return Ok(());
+        }
+    };
+    let service = match domain.services.get(&service_id) {
+        Some(s) => s,
+        None => {
+            println!("Unknown service: {}.{}", domain_id, service_id);
+            return Ok(());
+        }
+    };
+
+    let interactive = args.service.is_none();
+
+    let entity_id = match service.get_target_entity_domains() {
+        None => None,
+        Some(target_domains) => match &args.entity_id {
+            Some(id) => Some(id.clone()),
+            None if interactive => {
+                let entities = client.fetch_entities_by_domain(target_domains)?;
+                let entity_ids = entities.iter().map(|e| e.entity_id.as_str()).collect();
+                let i = prompt_for_selection("Entity", &entity_ids);
+                Some(entity_ids[i].to_string())
+            }
+            None => None,
+        },
     };
-    let entity_ids = entities.iter().map(|e| e.entity_id.as_str()).collect();
-    let i = prompt_for_selection("Entity", &entity_ids);
-    let entity_id = entity_ids[i];
 
-    let _ = client.call_service(domain_id, service_id, entity_id)?;
+    let mut payload = serde_json::Map::new();
+    if let Some(entity_id) = &entity_id {
+        payload.insert("entity_id".to_string(), Value::String(entity_id.clone()));
+    }
+
+    let provided: HashMap<&str, &str> = args
+        .data
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+
+    for (field_name, field) in service.fields.iter() {
+        if let Some(value) = provided.get(field_name.as_str()) {
+            payload.insert(field_name.clone(), parse_field_value(value));
+            continue;
+        }
+
+        if !interactive {
+            continue;
+        }
+
+        let prompt = field.name.clone().unwrap_or_else(|| field_name.clone());
+        let theme = ColorfulTheme::default();
+        let mut input = Input::<String>::with_theme(&theme)
+            .with_prompt(&prompt)
+            .allow_empty(true);
+        if let Some(default) = &field.default {
+            input = input.default(default_as_string(default));
+        }
+        let value = input.interact_text().unwrap_or_default();
+        if value.is_empty() {
+            continue;
+        }
+        payload.insert(field_name.clone(), parse_field_value(&value));
+    }
+
+    let _ = client.call_service(&domain_id, &service_id, payload)?;
 
     println!("Service called successfully");
     Ok(())
@@ -276,8 +482,54 @@ fn cmd_scene_enable(client: &Client, entity_id: String) {
     }
 }
 
+fn cmd_login(profile: Option<&str>) {
+    let config = Config::load();
+    let profile_name = profile
+        .map(str::to_string)
+        .or_else(|| config.default_profile.clone());
+    let profile_cfg = profile_name.as_deref().and_then(|name| config.profile(name));
+    let profile_key = profile_name.as_deref().unwrap_or(DEFAULT_PROFILE);
+
+    let host = match env::var(HOST_KEY)
+        .ok()
+        .or_else(|| profile_cfg.and_then(|p| p.host.clone()))
+    {
+        Some(h) => h,
+        None => {
+            println!(
+                "Missing host.\n\nSet the environment variable {} or add a host\nto this profile in the {} config file before logging in.",
+                HOST_KEY, NAME
+            );
+            return;
+        }
+    };
+
+    let token = match Password::with_theme(&ColorfulTheme::default())
+        .with_prompt("Home Assistant long-lived access token")
+        .interact()
+    {
+        Ok(t) => t,
+        Err(err) => {
+            println!("Failed to read token: {:?}", err);
+            return;
+        }
+    };
+
+    match keyring_entry(profile_key, &host).and_then(|e| e.set_password(&token)) {
+        Ok(_) => println!(
+            "Token saved in the OS keyring for profile '{}' on host '{}'.",
+            profile_key, host
+        ),
+        Err(err) => println!("Failed to save token to keyring: {:?}", err),
+    }
+}
+
 #[derive(Parser)]
 struct Cli {
+    /// Named profile to use, as defined in the config file.
+    #[arg(short, long)]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -288,10 +540,25 @@ enum Commands {
     Entity(EntityCli),
     Service(ServiceCli),
     Call(CallCli),
+    Login(LoginCli),
 }
 
 #[derive(Parser)]
-struct CallCli {}
+struct LoginCli {}
+
+#[derive(Parser)]
+struct CallCli {
+    /// Service to call, e.g. "light.turn_on". Prompts interactively when omitted.
+    service: Option<String>,
+
+    /// Entity ID to target.
+    #[arg(short, long)]
+    entity_id: Option<String>,
+
+    /// Field value as key=value, e.g. "--data brightness=200". May be repeated.
+    #[arg(long = "data", value_parser = parse_key_val)]
+    data: Vec<(String, String)>,
+}
 
 #[derive(Parser)]
 struct SceneCli {
@@ -316,6 +583,13 @@ struct EntityCli {
 enum EntityCommands {
     List,
     Show { entity_id: String },
+    Watch {
+        entity_id: Vec<String>,
+
+        /// Only watch entities in this domain (e.g. "light"). May be repeated.
+        #[arg(short, long)]
+        domain: Vec<String>,
+    },
 }
 
 #[derive(Parser)]
@@ -333,7 +607,12 @@ fn main() {
     dotenv::dotenv().ok();
     let cli = Cli::parse();
 
-    let client = match Client::setup() {
+    if let Commands::Login(_) = &cli.command {
+        cmd_login(cli.profile.as_deref());
+        return;
+    }
+
+    let client = match Client::setup(cli.profile.as_deref()) {
         Ok(c) => c,
         Err(e) => {
             println!("Failed to create client:\n\n{}", e.error_description());
@@ -350,12 +629,18 @@ fn main() {
         Commands::Entity(entity_cli) => match &entity_cli.command {
             EntityCommands::List => cmd_entity_list(&client),
             EntityCommands::Show { entity_id } => cmd_entity_show(&client, entity_id),
+            EntityCommands::Watch { entity_id, domain } => {
+                cmd_entity_watch(&client, entity_id.clone(), domain.clone())
+            }
         },
         Commands::Service(service_cli) => match &service_cli.command {
             ServiceCommands::List => cmd_service_list(&client),
         },
-        Commands::Call(_) => {
-            let _ = cmd_call(&client);
+        Commands::Call(call_cli) => {
+            if let Err(err) = cmd_call(&client, call_cli) {
+                println!("Failed to call service: {:?}", err);
+            }
         }
+        Commands::Login(_) => unreachable!("handled above"),
     }
 }
@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+
+use futures_util::stream::SplitStream;
+use futures_util::{SinkExt, StreamExt};
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::dto::WsMessage;
+
+/// Reads the next text frame and returns its `type` field, used to verify
+/// the `auth_required`/`auth_ok` handshake steps before we trust the socket.
+async fn next_message_type(
+    read: &mut SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+) -> Result<Option<String>, String> {
+    loop {
+        match read.next().await {
+            Some(Ok(Message::Text(text))) => {
+                return serde_json::from_str::<WsMessage>(&text)
+                    .map(|msg| Some(msg.kind))
+                    .map_err(|err| format!("Failed to parse handshake message: {}", err))
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(err)) => return Err(format!("Handshake failed: {}", err)),
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Connects to the Home Assistant WebSocket API, authenticates, subscribes
+/// to `state_changed` events and prints matching transitions to stdout
+/// until the socket closes or Ctrl-C is pressed.
+pub async fn watch(
+    scheme: &str,
+    host: &str,
+    port: u16,
+    access_token: &SecretString,
+    entity_ids: HashSet<String>,
+    domains: HashSet<String>,
+) -> Result<(), String> {
+    let ws_scheme = if scheme == "https" { "wss" } else { "ws" };
+    let url = format!("{}://{}:{}/api/websocket", ws_scheme, host, port);
+
+    let (ws_stream, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|err| format!("Failed to connect: {}", err))?;
+    let (mut write, mut read) = ws_stream.split();
+
+    match next_message_type(&mut read).await? {
+        Some(kind) if kind == "auth_required" => {}
+        Some(kind) => return Err(format!("Unexpected handshake message: {}", kind)),
+        None => return Err("Connection closed before authentication".into()),
+    }
+
+    write
+        .send(Message::Text(
+            json!({ "type": "auth", "access_token": access_token.expose_secret() }).to_string(),
+        ))
+        .await
+        .map_err(|err| format!("Failed to authenticate: {}", err))?;
+
+    match next_message_type(&mut read).await? {
+        Some(kind) if kind == "auth_ok" => {}
+        Some(kind) => return Err(format!("Authentication failed: {}", kind)),
+        None => return Err("Connection closed during authentication".into()),
+    }
+
+    write
+        .send(Message::Text(
+            json!({ "id": 1, "type": "subscribe_events", "event_type": "state_changed" })
+                .to_string(),
+        ))
+        .await
+        .map_err(|err| format!("Failed to subscribe: {}", err))?;
+
+    println!("Watching for state changes. Press Ctrl-C to stop.");
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                let _ = write.send(Message::Close(None)).await;
+                break;
+            }
+            msg = read.next() => {
+                let Some(Ok(msg)) = msg else { break };
+                handle_message(msg, &entity_ids, &domains);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_message(msg: Message, entity_ids: &HashSet<String>, domains: &HashSet<String>) {
+    let Message::Text(text) = msg else { return };
+    let Ok(parsed) = serde_json::from_str::<WsMessage>(&text) else {
+        return;
+    };
+    let Some(event) = parsed.event else { return };
+    if event.event_type != "state_changed" {
+        return;
+    }
+
+    let data = event.data;
+    if !entity_ids.is_empty() && !entity_ids.contains(&data.entity_id) {
+        return;
+    }
+    if !domains.is_empty() {
+        let domain = data.entity_id.split('.').next().unwrap_or_default();
+        if !domains.contains(domain) {
+            return;
+        }
+    }
+
+    let old_name = data
+        .old_state
+        .as_ref()
+        .map(|s| s.name())
+        .unwrap_or_else(|| data.entity_id.clone());
+    let old_state = data
+        .old_state
+        .as_ref()
+        .map(|s| s.state.clone())
+        .unwrap_or_else(|| "none".into());
+
+    match data.new_state {
+        Some(new_state) => {
+            println!("{}: {} -> {}", new_state.name(), old_state, new_state.state);
+            new_state.pretty_print(false);
+        }
+        None => println!("{} removed", old_name),
+    }
+}